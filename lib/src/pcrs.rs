@@ -3,19 +3,45 @@
 //
 // SPDX-License-Identifier: MIT
 
-use crate::tpmevents::TPMEvent;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::tpmevents::{TPMEvent, TPMEventID};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
-use sha2::{Digest, Sha256};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
 
-const PCR_INIT_VALUE: [u8; 32] = [
+pub(crate) const PCR_INIT_VALUE: [u8; 32] = [
     0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
 ];
 
 #[cfg(test)]
 mod tests;
 
+/// A TPM 2.0 PCR bank, as named by the TCG `TPM_ALG_ID` registry. Real
+/// devices extend several of these in parallel from the same event log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Algorithm {
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl Algorithm {
+    /// Width, in bytes, of an extend value in this bank.
+    pub fn digest_len(self) -> usize {
+        match self {
+            Algorithm::Sha1 => 20,
+            Algorithm::Sha256 => 32,
+            Algorithm::Sha384 => 48,
+            Algorithm::Sha512 => 64,
+        }
+    }
+}
+
 #[serde_as]
 #[derive(Clone, Serialize, Deserialize)]
 #[cfg_attr(test, derive(PartialEq, Debug))]
@@ -30,6 +56,7 @@ pub struct Part {
 #[cfg_attr(test, derive(PartialEq, Debug))]
 pub struct Pcr {
     pub id: u64,
+    pub algorithm: Algorithm,
     #[serde_as(as = "serde_with::hex::Hex")]
     pub value: Vec<u8>,
     pub parts: Vec<Part>,
@@ -44,34 +71,84 @@ impl From<&TPMEvent> for Part {
     }
 }
 
+/// Errors that can occur while folding a log of [`TPMEvent`]s into [`Pcr`] values.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PcrError {
+    /// An event claiming to belong to `found` was mixed into a sequence being
+    /// compiled for `expected`.
+    PcrMismatch { expected: u8, found: u8 },
+}
+
+impl std::fmt::Display for PcrError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PcrError::PcrMismatch { expected, found } => write!(
+                f,
+                "unexpected pcr#{found} while compiling pcr#{expected}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PcrError {}
+
 impl Pcr {
     /// Only supports compiling PCRs from vectors of events that belong
     /// to the same PCR
     /// Note that events must be ordered per PCR and the order TPM would
     /// expect them to be logged
-    pub fn compile_from(events: &Vec<TPMEvent>) -> Pcr {
-        let mut result = PCR_INIT_VALUE.to_vec();
+    ///
+    /// Replays into the SHA-256 bank; use [`Pcr::compile_from_bank`] for the
+    /// other TPM 2.0 banks.
+    pub fn compile_from(events: &Vec<TPMEvent>) -> Result<Pcr, PcrError> {
+        Self::compile_from_bank::<Sha256>(events, Algorithm::Sha256)
+    }
+
+    /// Same replay as [`Pcr::compile_from`], generalized over the digest
+    /// implementation `D` so non-SHA256 banks can be folded the same way.
+    /// `algorithm` only tags which bank `D` corresponds to in the result —
+    /// it isn't used to pick the hasher, so callers must pass a `D` whose
+    /// output width matches `algorithm.digest_len()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `D`'s output width doesn't match `algorithm.digest_len()`.
+    /// This is checked with a real `assert_eq!`, not `debug_assert_eq!`,
+    /// because a mismatch here silently tags a wrong-width digest as
+    /// belonging to `algorithm` in attestation data that release builds
+    /// still produce and ship.
+    pub fn compile_from_bank<D: Digest>(
+        events: &Vec<TPMEvent>,
+        algorithm: Algorithm,
+    ) -> Result<Pcr, PcrError> {
+        assert_eq!(
+            D::output_size(),
+            algorithm.digest_len(),
+            "digest D doesn't match the width of the {algorithm:?} bank it's tagged with"
+        );
+
+        let mut result = vec![0u8; D::output_size()];
         let compiled_pcr: u8 = events[0].pcr;
 
         for event in events {
             if event.pcr != compiled_pcr {
-                // FIXME: better error handling
-                panic!(
-                    "unexpected pcr#{} while compiling pcr#{}",
-                    event.pcr, compiled_pcr
-                );
+                return Err(PcrError::PcrMismatch {
+                    expected: compiled_pcr,
+                    found: event.pcr,
+                });
             }
-            let mut hasher = Sha256::new();
-            hasher.update(result);
-            hasher.update(event.hash.clone());
+            let mut hasher = D::new();
+            hasher.update(&result);
+            hasher.update(&event.hash);
             result = hasher.finalize().to_vec();
         }
 
-        Pcr {
+        Ok(Pcr {
             id: events[0].pcr.into(),
+            algorithm,
             value: result,
             parts: events.iter().map(|e| e.into()).collect(),
-        }
+        })
     }
 }
 
@@ -79,10 +156,109 @@ impl Pcr {
 /// to different PCRs
 /// Note that events must be ordered per PCR and the order TPM would
 /// expect them to be logged
-pub fn compile_pcrs(events: &[TPMEvent]) -> Vec<Pcr> {
+pub fn compile_pcrs(events: &[TPMEvent]) -> Result<Vec<Pcr>, PcrError> {
     let pcrs: Vec<u8> = events.iter().map(|e| e.pcr).unique().collect();
 
     pcrs.iter()
         .map(|n| Pcr::compile_from(&events.iter().filter(|e| e.pcr == *n).cloned().collect()))
         .collect()
 }
+
+/// Produces one [`Pcr`] per (pcr, algorithm) pair from a single event log,
+/// mirroring how a real TPM 2.0 device extends every configured bank from
+/// the same measurements and exposes them all in one quote.
+pub fn compile_pcrs_multi_bank(events: &[TPMEvent]) -> Result<Vec<Pcr>, PcrError> {
+    let pcrs: Vec<u8> = events.iter().map(|e| e.pcr).unique().collect();
+
+    pcrs.iter()
+        .flat_map(|n| {
+            let group: Vec<TPMEvent> = events.iter().filter(|e| e.pcr == *n).cloned().collect();
+            [
+                Pcr::compile_from_bank::<Sha1>(&group, Algorithm::Sha1),
+                Pcr::compile_from_bank::<Sha256>(&group, Algorithm::Sha256),
+                Pcr::compile_from_bank::<Sha384>(&group, Algorithm::Sha384),
+                Pcr::compile_from_bank::<Sha512>(&group, Algorithm::Sha512),
+            ]
+        })
+        .collect()
+}
+
+/// Errors caught while canonicalizing an unordered event log against the
+/// [`TPMEventID`] successor chain, before it is ever hashed into a [`Pcr`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OrderingError {
+    /// `id` never showed up while walking the successor chain from
+    /// [`TPMEventID::PcrRootNodeEvent`], so its logged position can't be
+    /// trusted.
+    OutOfOrder { id: TPMEventID },
+    /// `id` was logged more than once.
+    Duplicate { id: TPMEventID },
+    /// `id` was logged under `claimed_pcr`, but it belongs to a different PCR.
+    PcrMismatch { id: TPMEventID, claimed_pcr: u8 },
+    /// Canonicalization succeeded but folding the resulting groups failed.
+    Compile(PcrError),
+}
+
+impl std::fmt::Display for OrderingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrderingError::OutOfOrder { id } => {
+                write!(f, "event {id:?} never appears in the expected TPM replay order")
+            }
+            OrderingError::Duplicate { id } => write!(f, "event {id:?} was logged more than once"),
+            OrderingError::PcrMismatch { id, claimed_pcr } => write!(
+                f,
+                "event {id:?} was logged under pcr#{claimed_pcr} but doesn't belong to it"
+            ),
+            OrderingError::Compile(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for OrderingError {}
+
+/// Like [`compile_pcrs`], but doesn't trust the caller's ordering: events are
+/// grouped by PCR and re-sorted into canonical order by walking the
+/// [`TPMEventID`] successor chain from [`TPMEventID::PcrRootNodeEvent`],
+/// rather than assuming `events` is already in TPM replay order.
+///
+/// Returns an error for duplicate event IDs, events whose `id` doesn't belong
+/// to their claimed `pcr`, and events whose `id` never appears on the
+/// successor chain, instead of silently folding them into a wrong PCR value.
+pub fn compile_pcrs_checked(events: &[TPMEvent]) -> Result<Vec<Pcr>, OrderingError> {
+    let mut remaining: HashMap<TPMEventID, TPMEvent> = HashMap::new();
+    for event in events {
+        if event.id.pcr() != event.pcr {
+            return Err(OrderingError::PcrMismatch {
+                id: event.id.clone(),
+                claimed_pcr: event.pcr,
+            });
+        }
+        if remaining.insert(event.id.clone(), event.clone()).is_some() {
+            return Err(OrderingError::Duplicate {
+                id: event.id.clone(),
+            });
+        }
+    }
+
+    // BTreeMap, not HashMap: this function's whole point is producing a
+    // canonical, reproducible replay to compare against a device quote, and
+    // HashMap's iteration order isn't stable across processes.
+    let mut ordered: BTreeMap<u8, Vec<TPMEvent>> = BTreeMap::new();
+    let mut cursor = TPMEventID::PcrRootNodeEvent.next();
+    while let Some(id) = cursor {
+        if let Some(event) = remaining.remove(&id) {
+            ordered.entry(event.pcr).or_default().push(event);
+        }
+        cursor = id.next();
+    }
+
+    if let Some((id, _)) = remaining.into_iter().next() {
+        return Err(OrderingError::OutOfOrder { id });
+    }
+
+    ordered
+        .into_values()
+        .map(|group| Pcr::compile_from(&group).map_err(OrderingError::Compile))
+        .collect()
+}
@@ -32,6 +32,7 @@ fn test_part_deserialization() {
 fn test_pcr_serialization() {
     let input = Pcr {
         id: 123,
+        algorithm: Algorithm::Sha256,
         value: vec![0, 0, 0, 0, 0, 0, 0, 253],
         parts: vec![Part {
             name: "foo".into(),
@@ -39,7 +40,7 @@ fn test_pcr_serialization() {
         }],
     };
     let expected = String::from(
-        "{\"id\":123,\"value\":\"00000000000000fd\",\"parts\":[{\"name\":\"foo\",\"hash\":\"01000203ff\"}]}",
+        "{\"id\":123,\"algorithm\":\"SHA256\",\"value\":\"00000000000000fd\",\"parts\":[{\"name\":\"foo\",\"hash\":\"01000203ff\"}]}",
     );
 
     assert_eq!(serde_json::to_string(&input).unwrap(), expected);
@@ -49,6 +50,7 @@ fn test_pcr_serialization() {
 fn test_pcr_deserialization() {
     let expected = Pcr {
         id: 0,
+        algorithm: Algorithm::Sha256,
         value: vec![0, 0, 0, 0, 0, 0, 0, 240],
         parts: vec![Part {
             name: "foo".into(),
@@ -57,7 +59,7 @@ fn test_pcr_deserialization() {
     };
 
     let deserialized: Pcr = serde_json::from_str(
-        "{\"id\":0,\"value\":\"00000000000000f0\",\"parts\":[{\"name\":\"foo\",\"hash\":\"01000203ff\"}]}"
+        "{\"id\":0,\"algorithm\":\"SHA256\",\"value\":\"00000000000000f0\",\"parts\":[{\"name\":\"foo\",\"hash\":\"01000203ff\"}]}"
     ).unwrap();
 
     assert_eq!(deserialized, expected);
@@ -105,6 +107,7 @@ fn test_pcr_compilation_from_tpmevents() {
     ];
     let expected = Pcr {
         id: 4,
+        algorithm: Algorithm::Sha256,
         value: vec![
             65, 62, 10, 52, 9, 169, 42, 229, 47, 108, 155, 208, 62, 239, 192, 64, 254, 216, 40,
             213, 49, 150, 204, 191, 240, 146, 157, 233, 235, 71, 46, 91,
@@ -127,13 +130,12 @@ fn test_pcr_compilation_from_tpmevents() {
         ],
     };
 
-    let res = Pcr::compile_from(&input);
+    let res = Pcr::compile_from(&input).unwrap();
 
     assert_eq!(res, expected);
 }
 
 #[test]
-#[should_panic]
 fn test_pcr_compilation_fails_for_heterogeneous_vecs() {
     let input = vec![
         TPMEvent {
@@ -156,7 +158,15 @@ fn test_pcr_compilation_fails_for_heterogeneous_vecs() {
         },
     ];
 
-    Pcr::compile_from(&input);
+    let res = Pcr::compile_from(&input);
+
+    assert_eq!(
+        res,
+        Err(PcrError::PcrMismatch {
+            expected: 4,
+            found: 7
+        })
+    );
 }
 
 #[test]
@@ -185,6 +195,7 @@ fn test_pcr_compilation_from_heterogeneous_vec() {
     let expected = vec![
         Pcr {
             id: 4,
+            algorithm: Algorithm::Sha256,
             value: vec![
                 78, 5, 240, 197, 137, 1, 49, 110, 26, 17, 206, 213, 73, 16, 170, 53, 124, 15, 18,
                 16, 159, 35, 230, 209, 16, 42, 161, 172, 36, 158, 227, 74,
@@ -199,6 +210,7 @@ fn test_pcr_compilation_from_heterogeneous_vec() {
         },
         Pcr {
             id: 7,
+            algorithm: Algorithm::Sha256,
             value: vec![
                 144, 244, 179, 149, 72, 223, 85, 173, 97, 135, 161, 210, 13, 115, 30, 206, 231,
                 140, 84, 91, 148, 175, 209, 111, 66, 239, 117, 146, 217, 156, 211, 101,
@@ -213,7 +225,124 @@ fn test_pcr_compilation_from_heterogeneous_vec() {
         },
     ];
 
-    let res = compile_pcrs(&input);
+    let res = compile_pcrs(&input).unwrap();
 
     assert_eq!(res, expected);
 }
+
+#[test]
+fn test_compile_pcrs_checked_reorders_shuffled_log() {
+    let efi_call = TPMEvent {
+        name: "FOOBAR".into(),
+        pcr: 4,
+        hash: vec![
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0,
+        ],
+        id: TPMEventID::Pcr4EfiCall,
+    };
+    let separator = TPMEvent {
+        name: "BARFOO".into(),
+        pcr: 4,
+        hash: vec![
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 1,
+        ],
+        id: TPMEventID::Pcr4Separator,
+    };
+
+    // Logged out of TPM replay order; compile_pcrs_checked must canonicalize
+    // it back to the order compile_pcrs would expect to be handed directly.
+    let shuffled = vec![separator.clone(), efi_call.clone()];
+    let canonical = vec![efi_call, separator];
+
+    let checked = compile_pcrs_checked(&shuffled).unwrap();
+    let expected = compile_pcrs(&canonical).unwrap();
+
+    assert_eq!(checked, expected);
+}
+
+#[test]
+fn test_compile_pcrs_checked_detects_duplicate_ids() {
+    let make = || TPMEvent {
+        name: "FOOBAR".into(),
+        pcr: 4,
+        hash: vec![0; 32],
+        id: TPMEventID::Pcr4EfiCall,
+    };
+
+    let res = compile_pcrs_checked(&[make(), make()]);
+
+    assert_eq!(
+        res,
+        Err(OrderingError::Duplicate {
+            id: TPMEventID::Pcr4EfiCall
+        })
+    );
+}
+
+#[test]
+fn test_compile_pcrs_checked_detects_pcr_mismatch() {
+    let input = [TPMEvent {
+        name: "FOOBAR".into(),
+        pcr: 7,
+        hash: vec![0; 32],
+        id: TPMEventID::Pcr4EfiCall,
+    }];
+
+    let res = compile_pcrs_checked(&input);
+
+    assert_eq!(
+        res,
+        Err(OrderingError::PcrMismatch {
+            id: TPMEventID::Pcr4EfiCall,
+            claimed_pcr: 7
+        })
+    );
+}
+
+#[test]
+fn test_compile_pcrs_checked_detects_out_of_order_events() {
+    // PcrRootNodeEvent is the walk's starting sentinel, not a real logged
+    // event: compile_pcrs_checked's cursor begins at its successor, so a
+    // log that includes it can never find it while walking the chain.
+    let input = [TPMEvent {
+        name: "ROOT".into(),
+        pcr: TPMEventID::PcrRootNodeEvent.pcr(),
+        hash: vec![0; 32],
+        id: TPMEventID::PcrRootNodeEvent,
+    }];
+
+    let res = compile_pcrs_checked(&input);
+
+    assert_eq!(
+        res,
+        Err(OrderingError::OutOfOrder {
+            id: TPMEventID::PcrRootNodeEvent
+        })
+    );
+}
+
+#[test]
+fn test_compile_pcrs_checked_orders_pcrs_deterministically() {
+    let pcr7 = TPMEvent {
+        name: "BARFOO".into(),
+        pcr: 7,
+        hash: vec![0; 32],
+        id: TPMEventID::Pcr7SecureBoot,
+    };
+    let pcr4 = TPMEvent {
+        name: "FOOBAR".into(),
+        pcr: 4,
+        hash: vec![0; 32],
+        id: TPMEventID::Pcr4EfiCall,
+    };
+
+    // Logged pcr7-before-pcr4; output order must not depend on HashMap's
+    // randomized iteration order, only on the pcr id.
+    let input = [pcr7, pcr4];
+
+    let res = compile_pcrs_checked(&input).unwrap();
+
+    assert_eq!(res.iter().map(|pcr| pcr.id).collect::<Vec<_>>(), vec![4, 7]);
+}
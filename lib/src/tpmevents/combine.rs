@@ -69,9 +69,63 @@
 use std::collections::HashMap;
 
 use itertools::Itertools;
+use sha2::{Digest, Sha256};
 
 use super::*;
-use crate::pcrs::{Pcr, compile_pcrs};
+use crate::pcrs::{Algorithm, PCR_INIT_VALUE, Part, Pcr, PcrError};
+
+mod conflict;
+mod orset;
+mod report;
+pub use conflict::Conflict;
+pub use orset::GroupOwnership;
+pub use report::{ConflictReport, Derivation, Incompatibility};
+
+/// Running digest per PCR, keyed by the exact (input digest, event hash)
+/// pair being folded — i.e. by the *content* already hashed in, not by
+/// which `TPMEventID`s produced it. Two images can assert different
+/// `TPMEvent` values for the same id (that's exactly when `event_subtree`
+/// forks into sibling branches below); keying on ids alone would conflate
+/// those branches and let the second one reuse the first one's digest.
+/// Keying on content means branches that share a prefix still hit the
+/// cache, and branches that diverge can't collide, since a SHA256 digest
+/// already uniquely represents everything folded into it so far.
+type PcrCache = HashMap<(u8, Vec<u8>, Vec<u8>), Vec<u8>>;
+
+/// Same folding logic as [`crate::pcrs::compile_pcrs`], but threads `cache`
+/// through the SHA256 extend so sibling branches sharing a prefix of events
+/// don't re-hash it from scratch.
+fn compile_pcrs_cached(events: &[TPMEvent], cache: &mut PcrCache) -> Result<Vec<Pcr>, PcrError> {
+    let pcrs: Vec<u8> = events.iter().map(|e| e.pcr).unique().collect();
+
+    pcrs.into_iter()
+        .map(|pcr| {
+            let mut running = PCR_INIT_VALUE.to_vec();
+            let mut parts = vec![];
+            for event in events.iter().filter(|e| e.pcr == pcr) {
+                let cache_key = (pcr, running.clone(), event.hash.clone());
+                running = match cache.get(&cache_key) {
+                    Some(digest) => digest.clone(),
+                    None => {
+                        let mut hasher = Sha256::new();
+                        hasher.update(&running);
+                        hasher.update(event.hash.clone());
+                        let digest = hasher.finalize().to_vec();
+                        cache.insert(cache_key, digest.clone());
+                        digest
+                    }
+                };
+                parts.push(Part::from(event));
+            }
+            Ok(Pcr {
+                id: pcr.into(),
+                algorithm: Algorithm::Sha256,
+                value: running,
+                parts,
+            })
+        })
+        .collect()
+}
 
 #[cfg(test)]
 mod tests;
@@ -85,87 +139,108 @@ mod tests;
 //         .collect()
 // }
 
-pub fn combine(images: &Vec<Vec<TPMEvent>>) -> Vec<Vec<Pcr>> {
+/// Combines the event logs of several images into the set of PCR solutions
+/// they can produce.
+///
+/// Unlike panicking on the first unresolved event group, every branch comes
+/// back paired with the [`Conflict`]s hit anywhere in the walk (each branch
+/// carries the same list, not only its own), so a caller can
+/// still use the partial solution and hand the conflicts to [`compute_event`]
+/// and [`fold_recovered_event`] for recovery.
+pub fn combine(images: &Vec<Vec<TPMEvent>>) -> Vec<(Vec<Pcr>, Vec<Conflict<TPMEvent>>)> {
     let event_maps = images.iter().map(|i| tpm_event_id_hashmap(i)).collect();
-    let groups = vec![0; images.len()];
 
-    let event = TPMEventID::PcrRootNodeEvent.next().unwrap();
-    match event_subtree(&event, &event_maps, groups) {
-        Some(st) => st
-            .iter()
-            .flat_map(|t| t.branches())
-            .map(|e| compile_pcrs(&e))
-            .unique()
-            .collect(),
+    let Some(event) = TPMEventID::PcrRootNodeEvent.next() else {
+        return vec![];
+    };
+    let (subtree, conflicts) = event_subtree(
+        &event,
+        &event_maps,
+        GroupOwnership::new(),
+        images.len(),
+        vec![],
+    );
+    match subtree {
+        Some(st) => {
+            let mut cache = PcrCache::new();
+            st.iter()
+                .flat_map(|t| t.branches())
+                .map(|e| {
+                    let pcrs = compile_pcrs_cached(&e, &mut cache).expect(
+                        "event_subtree produced a branch mixing events from different pcrs",
+                    );
+                    (pcrs, conflicts.clone())
+                })
+                .collect()
+        }
         None => vec![],
     }
 }
 
-/// For recovery, we would need some information such as
-///     - pcr number
-///     - images involved in the conflict
-///         * Is everyone part of the conflict?
-///     - 
+/// Finds the first image, other than `exclude`, that asserts ownership of
+/// any group in `event_groups`.
+fn partial_owner(
+    ownership: &GroupOwnership,
+    event_groups: u32,
+    num_images: usize,
+    exclude: usize,
+) -> Option<usize> {
+    (0..num_images).find(|&i| i != exclude && ownership.asserts_any(i, event_groups))
+}
+
+/// Walks the event tree for `event_id` and beyond, returning the resulting
+/// subtree together with every [`Conflict`] hit along the way.
+///
+/// `trace` is the chain of [`Derivation`]s recorded on the path from the
+/// root event down to this call; each conflict records a clone of it as the
+/// derivations leading to its [`Incompatibility`].
+///
+/// A `None` subtree means the walk produced no node from `event_id` onward:
+/// either a required event (this one, or a later one when no image logged
+/// this one) was unresolvable, in which case the conflict explaining why is
+/// still returned, or no image logged `event_id` and it is the last event.
 fn event_subtree(
     event_id: &TPMEventID,
     event_maps: &Vec<HashMap<TPMEventID, TPMEvent>>,
-    groups: Vec<u32>,
-) -> Option<Vec<tree::EventNode<TPMEvent>>> {
+    ownership: GroupOwnership,
+    num_images: usize,
+    trace: Vec<Derivation>,
+) -> (Option<Vec<tree::EventNode<TPMEvent>>>, Vec<Conflict<TPMEvent>>) {
     let event_groups = event_id.groups();
     let opts: Vec<_> = event_maps.iter().map(|m| m.get(event_id)).collect();
-    // Divergences represent reasons why the tree might diverge
-    let mut divs: Vec<(&TPMEvent, Vec<u32>)> = vec![];
+    // Divergences represent reasons why the tree might diverge, each carrying
+    // the representative image that asserted it
+    let mut divs: Vec<(&TPMEvent, GroupOwnership, usize)> = vec![];
     let mut nodes: Vec<tree::EventNode<TPMEvent>> = vec![];
     let mut event_required = true;
     // Relates TPMEvents and their global index and div index
     let mut events_added: HashMap<TPMEvent, (Vec<usize>, usize)> = HashMap::new();
-    let mut conflicts: Vec<usize> = vec![];
-
-    println!("-----------------------------------------------------------------");
-    println!("PCR Event {event_id:?}");
-    println!("Groups needed:   {:#034b}", event_groups);
-    for (j, g) in groups.iter().enumerate() {
-        println!("Group {j} has:     {:#034b}", g);
-    }
-    println!("");
+    let mut conflicting_images: Vec<usize> = vec![];
+    let mut conflicts: Vec<Conflict<TPMEvent>> = vec![];
 
     for (i, opt) in opts.iter().enumerate() {
         match opt {
             Some(event) => {
                 // FIXME: Should we check if the missing groups we need to lock
                 //        aren't locked by anyone else?
-                if can_own(i, &groups, event_groups) {
+                if ownership.can_own(i, event_groups) {
                     let (global_ids, div_idx) = events_added
                         .entry((*event).clone())
                         .or_insert_with(|| (vec![], divs.len()));
 
                     global_ids.push(i);
                     if divs.len() == *div_idx {
-                        divs.push((&event, groups.clone()));
+                        divs.push((&event, ownership.clone(), i));
                     }
 
-                    let mut masked_groups = divs[*div_idx].1.clone();
-                    masked_groups[i] |= event_groups;
-                    divs[*div_idx].1 = masked_groups;
-                    println!("Pushing image {i}, total divs: {}", divs.len());
-                    println!("Groups masked:   {:#034b}", divs[*div_idx].1[i]);
-                //} else if !other_owns_fully(i, &groups, event_groups) {
-                } else if other_owns_partially(i, &groups, event_groups) && !other_owns_fully(i, &groups, event_groups) {
-                    // conflict pairs.
-                    // We need to know i
-                    // and who is locking those groups that we are missing
-                    //
-                    // NOTE: Is it different when
-                    //  - Others partially own a group
-                    //      - This means we're facing a conflict
-                    //  - Others completely own a group
-                    //      - I think this would mean we're filling another
-                    //        branch that we don't care about.
-                    println!("Considering conflict");
-                    println!("\tImage {i}");
-                    println!("\tFully owned? {}", fully_owned(groups[i], event_groups));
-                    println!("\tPartly owned? {}", other_owns_partially(i, &groups, event_groups));
-                    conflicts.push(i)
+                    divs[*div_idx].1.observe(i, event_groups);
+                } else if ownership.owned_partially_by_other(i, event_groups)
+                    && !ownership.owned_fully_by_other(i, event_groups)
+                {
+                    // Others partially owning our missing groups means we're
+                    // facing a genuine conflict; others fully owning them
+                    // just means we're filling a branch we don't care about.
+                    conflicting_images.push(i)
                 }
             }
             None => event_required = false,
@@ -175,90 +250,145 @@ fn event_subtree(
     if events_added.len() == 1 && event_required {
         divs = events_added
             .iter()
-            .map(|(e, _)| (e, groups.clone()))
+            .map(|(e, (ids, _))| (e, ownership.clone(), ids[0]))
             .collect()
     }
 
-    //if !conflicts.is_empty() && divs.is_empty() {
-    if !conflicts.is_empty() {
-        panic!("NEW EVENT GROUP DETECTION ALG");
+    if !conflicting_images.is_empty() {
+        let candidates: Vec<TPMEvent> = opts.iter().filter_map(|o| o.cloned()).unique().collect();
+        let image = conflicting_images[0];
+        let other_image =
+            partial_owner(&ownership, event_groups, num_images, image).unwrap_or(image);
+        let other_owns = ownership.owned_mask(other_image);
+        conflicts.push(Conflict {
+            event_id: event_id.clone(),
+            images_involved: conflicting_images,
+            candidates,
+            report: ConflictReport::new(
+                trace.clone(),
+                Some(Incompatibility {
+                    event_id: event_id.clone(),
+                    image,
+                    needs: event_groups,
+                    other_image,
+                    other_owns,
+                }),
+                event_groups | other_owns,
+            ),
+        });
     }
 
     if divs.is_empty() {
         // Event is required but wasn't pushed to divergences...
         // Means we met an event id/tree branching group conflict
         if event_required {
-            // NOTE: (remove) It's impossible that conflicts.is_empty() now
-            // TODO: switch from panic to result?
-            println!("N divs: {}" ,divs.len());
-            println!("Conflicts: {:?}", conflicts);
-            panic!("Event group conflict hit");
+            // If conflicting_images wasn't empty, the detailed conflict
+            // above already explains this exact dead end; don't also push
+            // this generic, less informative one for the same event_id.
+            if conflicting_images.is_empty() {
+                conflicts.push(Conflict {
+                    event_id: event_id.clone(),
+                    images_involved: (0..num_images).collect(),
+                    candidates: opts.iter().filter_map(|o| o.cloned()).unique().collect(),
+                    report: ConflictReport::new(trace, None, event_groups),
+                });
+            }
+            return (None, conflicts);
         }
-        println!("\n\n");
-        return event_subtree(&event_id.next()?, event_maps, groups);
+        return match event_id.next() {
+            Some(next) => {
+                let (subtree, sub_conflicts) =
+                    event_subtree(&next, event_maps, ownership, num_images, trace);
+                conflicts.extend(sub_conflicts);
+                (subtree, conflicts)
+            }
+            None => (None, conflicts),
+        };
     }
 
-    for (event, group_masks) in divs {
+    for (event, branch_ownership, image) in divs {
         let mut node = tree::EventNode::<TPMEvent>::new(event.clone());
-        if let Some(children) = event_subtree(&event_id.next()?, &event_maps, group_masks.clone()) {
-            for c in children {
-                node.add_child(c);
+        if let Some(next) = event_id.next() {
+            let mut branch_trace = trace.clone();
+            branch_trace.push(Derivation {
+                event_id: event_id.clone(),
+                groups_needed: event_groups,
+                image,
+                locked_groups: branch_ownership.owned_mask(image),
+            });
+            let (children, sub_conflicts) = event_subtree(
+                &next,
+                event_maps,
+                branch_ownership,
+                num_images,
+                branch_trace,
+            );
+            conflicts.extend(sub_conflicts);
+            if let Some(children) = children {
+                for c in children {
+                    node.add_child(c);
+                }
             }
         }
         nodes.push(node);
     }
 
-    println!("pushed {} nodes", nodes.len());
-    println!("\n\n");
-
-    Some(nodes)
+    (Some(nodes), conflicts)
 }
 
-fn tpm_event_id_hashmap(events: &[TPMEvent]) -> HashMap<TPMEventID, TPMEvent> {
-    events.iter().map(|e| (e.id.clone(), e.clone())).collect()
+/// Recovery entry point for a [`Conflict`] that [`combine`] reported for
+/// `event_id`, taking the paths of the mounted images involved. An `Ok`
+/// result is a single [`TPMEvent`], which [`fold_recovered_event`] folds back
+/// into the solution.
+///
+/// Measuring events from mounted images is not implemented: this always
+/// returns [`ComputeError::NotImplemented`] and does not read `paths`.
+pub fn compute_event(event_id: TPMEventID, paths: &[&str]) -> Result<TPMEvent, ComputeError> {
+    let _ = paths;
+    Err(ComputeError::NotImplemented { event_id })
 }
 
-fn group_masks_overlap(groups: &[u32]) -> bool {
-    let mut sum: u32 = 0;
-
-    for group in groups.iter() {
-        if sum & group != 0 {
-            return true;
+/// Folds a [`TPMEvent`] recovered via [`compute_event`] back into `images`
+/// and re-[`combine`]s, so resolving one conflict produces an updated
+/// solution set instead of leaving the caller to patch and re-run by hand.
+///
+/// Replaces whatever each of `conflict.images_involved` had logged for
+/// `conflict.event_id` with `recovered` before re-combining.
+pub fn fold_recovered_event(
+    images: &Vec<Vec<TPMEvent>>,
+    conflict: &Conflict<TPMEvent>,
+    recovered: TPMEvent,
+) -> Vec<(Vec<Pcr>, Vec<Conflict<TPMEvent>>)> {
+    let mut patched = images.clone();
+    for &image in &conflict.images_involved {
+        if let Some(events) = patched.get_mut(image) {
+            events.retain(|e| e.id != conflict.event_id);
+            events.push(recovered.clone());
         }
-        sum |= group;
     }
-
-    false
+    combine(&patched)
 }
 
-// Checks if any of the other images owns any required group previously
-fn other_owns_partially(owner_index: usize, owned_groups: &Vec<u32>, event_groups: u32) -> bool {
-    owned_groups
-        .iter()
-        .enumerate()
-        .filter(|(i, e)| *i != owner_index && partially_owned(**e, event_groups))
-        .count()
-        != 0
+/// Errors from [`compute_event`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ComputeError {
+    /// Measuring `event_id` from mounted images isn't implemented yet.
+    NotImplemented { event_id: TPMEventID },
 }
 
-fn partially_owned(owner: u32, groups: u32) -> bool {
-    groups & owner != 0
+impl std::fmt::Display for ComputeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ComputeError::NotImplemented { event_id } => write!(
+                f,
+                "measuring {event_id:?} from mounted images isn't implemented yet"
+            ),
+        }
+    }
 }
 
-fn fully_owned(owner: u32, groups: u32) -> bool {
-    (owner & groups) == groups
-}
+impl std::error::Error for ComputeError {}
 
-fn other_owns_fully(owner_index: usize, owned_groups: &Vec<u32>, event_groups: u32) -> bool {
-    owned_groups
-        .iter()
-        .enumerate()
-        .filter(|(i, e)| *i != owner_index && fully_owned(**e, event_groups))
-        .count()
-        != 0
-}
-
-fn can_own(owner_index: usize, owned_groups: &Vec<u32>, event_groups: u32) -> bool {
-    let missing_groups = !owned_groups[owner_index] & event_groups;
-    !other_owns_partially(owner_index, owned_groups, missing_groups)
+fn tpm_event_id_hashmap(events: &[TPMEvent]) -> HashMap<TPMEventID, TPMEvent> {
+    events.iter().map(|e| (e.id.clone(), e.clone())).collect()
 }
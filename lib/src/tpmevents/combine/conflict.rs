@@ -0,0 +1,23 @@
+// SPDX-FileCopyrightText: Beñat Gartzia Arruabarrena <bgartzia@redhat.com>
+//
+// SPDX-License-Identifier: MIT
+
+use super::report::ConflictReport;
+use crate::tpmevents::TPMEventID;
+
+/// An event whose groups were split across images in a way nothing could
+/// arbitrate, recorded by [`super::event_subtree`] instead of aborting the
+/// whole computation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conflict<T> {
+    /// The event that could not be resolved.
+    pub event_id: TPMEventID,
+    /// Indices, into the input image vector, of the images whose groups
+    /// crossed and caused the conflict.
+    pub images_involved: Vec<usize>,
+    /// The distinct values the input images asserted for this event, if any
+    /// were observed.
+    pub candidates: Vec<T>,
+    /// The causal chain of decisions that led to this conflict.
+    pub report: ConflictReport,
+}
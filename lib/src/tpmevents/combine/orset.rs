@@ -0,0 +1,102 @@
+// SPDX-FileCopyrightText: Beñat Gartzia Arruabarrena <bgartzia@redhat.com>
+//
+// SPDX-License-Identifier: MIT
+
+use std::collections::{HashMap, HashSet};
+
+/// Add-wins observed-remove set tracking, for every group id, which image
+/// indices ("dots") currently assert ownership of it.
+///
+/// Replaces the old per-image `u32` bitmask machinery: merging images is a
+/// union of dot sets, a group stays live for an image once observed, and two
+/// images only conflict over a group when their dot sets for it are
+/// disjoint. Group ids are decoded from the `u32` bitmask that
+/// [`crate::tpmevents::TPMEventID::groups`] returns, and the methods below
+/// take and return such masks, so at most 32 groups (ids 0 to 31) are tracked.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GroupOwnership {
+    dots: HashMap<u32, HashSet<usize>>,
+}
+
+/// Expands a `groups()` bitmask into the individual group ids it asserts.
+fn bits(mask: u32) -> impl Iterator<Item = u32> + Clone {
+    (0..32).filter(move |b| mask & (1 << b) != 0)
+}
+
+impl GroupOwnership {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that `image` observed (asserts ownership of) every group set
+    /// in `groups`. Add-wins: once observed, a dot is never removed by a
+    /// concurrent observation from another image.
+    pub fn observe(&mut self, image: usize, groups: u32) {
+        for group in bits(groups) {
+            self.dots.entry(group).or_default().insert(image);
+        }
+    }
+
+    pub fn is_owned_by(&self, image: usize, group: u32) -> bool {
+        self.dots
+            .get(&group)
+            .is_some_and(|owners| owners.contains(&image))
+    }
+
+    /// All groups `image`'s dots currently cover, folded back into a bitmask
+    /// for reporting purposes.
+    pub fn owned_mask(&self, image: usize) -> u32 {
+        self.dots.iter().fold(0u32, |mask, (group, owners)| {
+            if owners.contains(&image) {
+                mask | (1 << group)
+            } else {
+                mask
+            }
+        })
+    }
+
+    /// True if some image other than `image` asserts ownership of any group
+    /// in `groups` — i.e. the dot sets for those groups aren't exclusively
+    /// `image`'s.
+    pub fn owned_partially_by_other(&self, image: usize, groups: u32) -> bool {
+        bits(groups).any(|group| {
+            self.dots
+                .get(&group)
+                .is_some_and(|owners| owners.iter().any(|&other| other != image))
+        })
+    }
+
+    /// True if a single other image's dots fully cover `groups` — the two
+    /// images are just filling the same branch, not conflicting.
+    pub fn owned_fully_by_other(&self, image: usize, groups: u32) -> bool {
+        let mut common: Option<HashSet<usize>> = None;
+        for group in bits(groups) {
+            let owners = self.dots.get(&group).cloned().unwrap_or_default();
+            common = Some(match common {
+                None => owners,
+                Some(current) => current.intersection(&owners).copied().collect(),
+            });
+            if common.as_ref().is_some_and(HashSet::is_empty) {
+                return false;
+            }
+        }
+        common.is_some_and(|owners| owners.iter().any(|&other| other != image))
+    }
+
+    /// True if `image` asserts ownership of any group in `groups`.
+    pub fn asserts_any(&self, image: usize, groups: u32) -> bool {
+        bits(groups).any(|group| self.is_owned_by(image, group))
+    }
+
+    /// True if `image` can claim `groups` without crossing another image
+    /// that already partially asserts a group `image` doesn't own yet.
+    pub fn can_own(&self, image: usize, groups: u32) -> bool {
+        let missing: u32 = bits(groups)
+            .filter(|&group| !self.is_owned_by(image, group))
+            .fold(0u32, |mask, group| mask | (1 << group));
+        !self.owned_partially_by_other(image, missing)
+    }
+}
+
+#[cfg(test)]
+mod tests;
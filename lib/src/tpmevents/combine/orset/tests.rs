@@ -0,0 +1,98 @@
+// SPDX-FileCopyrightText: Beñat Gartzia Arruabarrena <bgartzia@redhat.com>
+//
+// SPDX-License-Identifier: MIT
+
+use super::*;
+
+#[test]
+fn test_observe_and_is_owned_by() {
+    let mut ownership = GroupOwnership::new();
+
+    assert!(!ownership.is_owned_by(0, 0));
+
+    ownership.observe(0, 0b01);
+
+    assert!(ownership.is_owned_by(0, 0));
+    assert!(!ownership.is_owned_by(1, 0));
+}
+
+#[test]
+fn test_observe_is_add_wins() {
+    let mut ownership = GroupOwnership::new();
+
+    ownership.observe(0, 0b01);
+    ownership.observe(1, 0b01);
+
+    assert!(ownership.is_owned_by(0, 0));
+    assert!(ownership.is_owned_by(1, 0));
+}
+
+#[test]
+fn test_owned_mask_folds_observed_groups() {
+    let mut ownership = GroupOwnership::new();
+
+    ownership.observe(0, 0b101);
+
+    assert_eq!(ownership.owned_mask(0), 0b101);
+    assert_eq!(ownership.owned_mask(1), 0);
+}
+
+#[test]
+fn test_owned_partially_by_other() {
+    let mut ownership = GroupOwnership::new();
+    ownership.observe(1, 0b01);
+
+    assert!(ownership.owned_partially_by_other(0, 0b01));
+    assert!(!ownership.owned_partially_by_other(0, 0b10));
+    assert!(!ownership.owned_partially_by_other(1, 0b01));
+}
+
+#[test]
+fn test_owned_fully_by_other_requires_single_common_owner() {
+    let mut ownership = GroupOwnership::new();
+    ownership.observe(1, 0b11);
+
+    assert!(ownership.owned_fully_by_other(0, 0b11));
+}
+
+#[test]
+fn test_owned_fully_by_other_false_when_groups_split_across_images() {
+    let mut ownership = GroupOwnership::new();
+    ownership.observe(1, 0b01);
+    ownership.observe(2, 0b10);
+
+    // No single other image owns both groups, just the images combined.
+    assert!(!ownership.owned_fully_by_other(0, 0b11));
+}
+
+#[test]
+fn test_asserts_any() {
+    let mut ownership = GroupOwnership::new();
+    ownership.observe(0, 0b10);
+
+    assert!(ownership.asserts_any(0, 0b11));
+    assert!(!ownership.asserts_any(1, 0b11));
+}
+
+#[test]
+fn test_can_own_when_unclaimed() {
+    let ownership = GroupOwnership::new();
+
+    assert!(ownership.can_own(0, 0b11));
+}
+
+#[test]
+fn test_can_own_false_when_other_partially_owns_missing_groups() {
+    let mut ownership = GroupOwnership::new();
+    ownership.observe(1, 0b10);
+
+    assert!(!ownership.can_own(0, 0b11));
+}
+
+#[test]
+fn test_can_own_true_when_image_already_owns_the_groups() {
+    let mut ownership = GroupOwnership::new();
+    ownership.observe(0, 0b11);
+
+    assert!(ownership.can_own(0, 0b11));
+}
@@ -0,0 +1,109 @@
+// SPDX-FileCopyrightText: Beñat Gartzia Arruabarrena <bgartzia@redhat.com>
+//
+// SPDX-License-Identifier: MIT
+
+use std::fmt;
+
+use crate::tpmevents::TPMEventID;
+
+/// One step of the search: to satisfy `event_id` (which needs `groups_needed`)
+/// we selected `image`, which locked `locked_groups` for it going forward.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Derivation {
+    pub event_id: TPMEventID,
+    pub groups_needed: u32,
+    pub image: usize,
+    pub locked_groups: u32,
+}
+
+impl fmt::Display for Derivation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "to satisfy {:?} (groups {:#06b}) selected image {}, which locked groups {:#06b}",
+            self.event_id, self.groups_needed, self.image, self.locked_groups
+        )
+    }
+}
+
+/// The terminal dead end: `image` needs `needs` for `event_id`, but
+/// `other_image` already owns the overlapping groups `other_owns`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Incompatibility {
+    pub event_id: TPMEventID,
+    pub image: usize,
+    pub needs: u32,
+    pub other_image: usize,
+    pub other_owns: u32,
+}
+
+impl fmt::Display for Incompatibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "image {} needs groups {:#06b} for {:?} but image {} already owns overlapping groups {:#06b}",
+            self.image, self.needs, self.event_id, self.other_image, self.other_owns
+        )
+    }
+}
+
+/// A minimal, human-readable explanation of why [`super::event_subtree`] hit
+/// a dead end: the chain of image selections that led there, followed by the
+/// terminal [`Incompatibility`] that made the next step impossible.
+///
+/// Mirrors a version solver's incompatibility/derivation graph: rather than
+/// dumping the whole search, it reports only the shortest causal path to the
+/// failing event, so an operator knows exactly which image/group pair to
+/// reconcile.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConflictReport {
+    pub derivations: Vec<Derivation>,
+    pub incompatibility: Option<Incompatibility>,
+}
+
+impl ConflictReport {
+    /// Builds a report from the full decision `trace`, pruning it down to
+    /// the minimal causal chain that explains `target_groups` (the groups at
+    /// stake in the failing event) instead of keeping the whole search path.
+    ///
+    /// Walks `trace` backward from the failing event: a derivation is kept
+    /// only if it locked a group we still need explained, in which case the
+    /// groups *it* needed are folded into what we're looking for next,
+    /// mirroring how a version solver reports the shortest causal path
+    /// through its derivation graph rather than the whole search.
+    pub fn new(
+        trace: Vec<Derivation>,
+        incompatibility: Option<Incompatibility>,
+        target_groups: u32,
+    ) -> Self {
+        let mut needed = target_groups;
+        let mut derivations = vec![];
+        for derivation in trace.into_iter().rev() {
+            if derivation.locked_groups & needed != 0 {
+                needed |= derivation.groups_needed;
+                derivations.push(derivation);
+            }
+        }
+        derivations.reverse();
+
+        ConflictReport {
+            derivations,
+            incompatibility,
+        }
+    }
+}
+
+impl fmt::Display for ConflictReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for derivation in &self.derivations {
+            writeln!(f, "- {derivation}")?;
+        }
+        if let Some(incompatibility) = &self.incompatibility {
+            writeln!(f, "- {incompatibility}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,52 @@
+// SPDX-FileCopyrightText: Beñat Gartzia Arruabarrena <bgartzia@redhat.com>
+//
+// SPDX-License-Identifier: MIT
+
+use super::*;
+use crate::tpmevents::TPMEventID;
+
+fn derivation(groups_needed: u32, image: usize, locked_groups: u32) -> Derivation {
+    Derivation {
+        event_id: TPMEventID::Pcr4EfiCall,
+        groups_needed,
+        image,
+        locked_groups,
+    }
+}
+
+#[test]
+fn test_new_keeps_only_the_causal_chain() {
+    // Step 0 locked group 1 while needing no groups at all — never on the path
+    // back from what the conflict needed, so it's pruned. Step 1 locked
+    // group 2 while chasing group 4 (what the conflict needed); step 2
+    // locked group 4 while chasing group 2 (needed in turn by step 1).
+    let trace = vec![
+        derivation(0b000, 0, 0b001),
+        derivation(0b100, 1, 0b010),
+        derivation(0b010, 0, 0b100),
+    ];
+
+    let report = ConflictReport::new(trace, None, 0b100);
+
+    assert_eq!(report.derivations.len(), 2);
+    assert_eq!(report.derivations[0].locked_groups, 0b010);
+    assert_eq!(report.derivations[1].locked_groups, 0b100);
+}
+
+#[test]
+fn test_new_keeps_full_causal_chain_when_every_step_is_relevant() {
+    let trace = vec![derivation(0b010, 0, 0b001), derivation(0b001, 1, 0b010)];
+
+    let report = ConflictReport::new(trace.clone(), None, 0b010);
+
+    assert_eq!(report.derivations, trace);
+}
+
+#[test]
+fn test_new_drops_everything_when_nothing_is_relevant() {
+    let trace = vec![derivation(0b010, 0, 0b100)];
+
+    let report = ConflictReport::new(trace, None, 0b001);
+
+    assert!(report.derivations.is_empty());
+}